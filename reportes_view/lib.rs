@@ -1,141 +1,662 @@
+/*
+Contrato 2 – ReportesView (solo lectura)
+Genera reportes a partir de los datos públicos de MarketplacePrincipal.
+Este contrato solo puede leer datos del contrato 1: no puede emitir
+calificaciones, modificar órdenes ni publicar productos.
+*/
+
 #![cfg_attr(not(feature = "std"), no_std, no_main)]
 
 #[ink::contract]
 mod reportes_view {
+    use ink::env::call::FromAccountId;
+    use ink::prelude::collections::BTreeMap;
+    use ink::prelude::string::String;
+    use ink::prelude::vec::Vec;
+    use ink::ToAccountId;
+    use marketplace_principal::marketplace_principal::{
+        Categoria,
+        EstadoOrden,
+        MarketplacePrincipalRef,
+        Moneda,
+        Orden,
+        Producto,
+        RolUsuario,
+        Usuario,
+        VentasCategoria,
+        MAX_RESULTADOS_POR_PAGINA,
+    };
+
+    /// Cantidad mínima de calificaciones para aparecer en los rankings de
+    /// reputación; evita que una única calificación alta encabece la lista.
+    pub const MINIMO_CALIFICACIONES_RANKING: u32 = 3;
 
-    /// Defines the storage of your contract.
-    /// Add new fields to the below struct in order
-    /// to add new static storage fields to your contract.
+    /// Estructura principal del contrato de reportes.
     #[ink(storage)]
     pub struct ReportesView {
-        /// Stores a single `bool` value on the storage.
-        value: bool,
-
-        
+        /// Referencia al contrato MarketplacePrincipal del que se leen los datos.
+        marketplace: MarketplacePrincipalRef,
     }
 
     impl ReportesView {
-        /// Constructor that initializes the `bool` value to the given `init_value`.
+        /// Crea el contrato de reportes apuntando a un MarketplacePrincipal ya desplegado.
         #[ink(constructor)]
-        pub fn new(init_value: bool) -> Self {
-            Self { value: init_value }
+        pub fn new(marketplace: AccountId) -> Self {
+            Self {
+                marketplace: MarketplacePrincipalRef::from_account_id(marketplace),
+            }
+        }
+
+        /// Retorna la dirección del MarketplacePrincipal del que se leen los datos.
+        #[ink(message)]
+        pub fn marketplace(&self) -> AccountId {
+            self.marketplace.to_account_id()
         }
 
-        /// Constructor that initializes the `bool` value to `false`.
+        /// Obtiene la información de un usuario registrado en el marketplace.
         ///
-        /// Constructors can delegate to other constructors.
-        #[ink(constructor)]
-        pub fn default() -> Self {
-            Self::new(Default::default())
+        /// # Retorna
+        /// - `Some(Usuario)` si el usuario está registrado.
+        /// - `None` si el usuario no está registrado.
+        #[ink(message)]
+        pub fn obtener_usuario(&self, usuario: AccountId) -> Option<Usuario> {
+            self.marketplace.obtener_usuario(usuario)
+        }
+
+        /// Obtiene un producto publicado en el marketplace.
+        ///
+        /// # Retorna
+        /// - `Some(Producto)` si el producto existe.
+        /// - `None` si no existe un producto con ese id.
+        #[ink(message)]
+        pub fn obtener_producto(&self, producto_id: u32) -> Option<Producto> {
+            self.marketplace.obtener_producto(producto_id)
+        }
+
+        /// Obtiene una orden del marketplace.
+        ///
+        /// # Retorna
+        /// - `Some(Orden)` si la orden existe.
+        /// - `None` si no existe una orden con ese id.
+        #[ink(message)]
+        pub fn obtener_orden(&self, orden_id: u32) -> Option<Orden> {
+            self.marketplace.obtener_orden(orden_id)
+        }
+
+        /// Retorna la cantidad de productos publicados en el marketplace.
+        #[ink(message)]
+        pub fn cantidad_productos(&self) -> u32 {
+            self.marketplace.cantidad_productos()
         }
 
-        /// A message that can be called on instantiated contracts.
-        /// This one flips the value of the stored `bool` from `true`
-        /// to `false` and vice versa.
+        /// Retorna la cantidad de órdenes creadas en el marketplace.
         #[ink(message)]
-        pub fn flip(&mut self) {
-            self.value = !self.value;
+        pub fn cantidad_ordenes(&self) -> u32 {
+            self.marketplace.cantidad_ordenes()
         }
 
-        /// Simply returns the current value of our `bool`.
+        /// Retorna el total histórico de comisiones cobradas por el marketplace en
+        /// `moneda` (la nativa o un token PSP22).
+        #[ink(message)]
+        pub fn total_comisiones_cobradas(&self, moneda: Moneda) -> Balance {
+            self.marketplace.comisiones_cobradas(moneda)
+        }
+
+        /// Consulta los `n` vendedores con mejor reputación entre los usuarios
+        /// registrados a partir de la posición `desde` del índice de usuarios
+        /// (como máximo `MAX_RESULTADOS_POR_PAGINA`).
+        ///
+        /// Cada llamada lee una sola página de usuarios; para el ranking completo
+        /// se recorren las páginas con `siguiente` y se conservan los `n` mejores
+        /// de la unión, que siempre están entre los `n` mejores de cada página.
+        /// Solo se consideran usuarios con al menos `MINIMO_CALIFICACIONES_RANKING`
+        /// calificaciones como vendedor. El promedio se expresa multiplicado por
+        /// 100 (por ejemplo, `450` equivale a 4,5 estrellas).
         #[ink(message)]
-        pub fn get(&self) -> bool {
-            self.value
+        pub fn top_vendedores(&self, desde: u32, cantidad: u32, n: u32) -> PaginaReporte<ReputacionUsuario> {
+            self.ranking_de_usuarios(RolUsuario::Vendedor, desde, cantidad, n)
+        }
+
+        /// Consulta los `n` compradores con mejor reputación entre los usuarios
+        /// registrados a partir de la posición `desde`, de a páginas como
+        /// `top_vendedores`.
+        ///
+        /// Solo se consideran usuarios con al menos `MINIMO_CALIFICACIONES_RANKING`
+        /// calificaciones como comprador.
+        #[ink(message)]
+        pub fn top_compradores(&self, desde: u32, cantidad: u32, n: u32) -> PaginaReporte<ReputacionUsuario> {
+            self.ranking_de_usuarios(RolUsuario::Comprador, desde, cantidad, n)
+        }
+
+        /// Arma el ranking del rol indicado sobre una página del índice de usuarios.
+        fn ranking_de_usuarios(&self, rol: RolUsuario, desde: u32, cantidad: u32, n: u32) -> PaginaReporte<ReputacionUsuario> {
+            let usuarios = self.marketplace.listar_usuarios(desde, cantidad);
+            let siguiente = posicion_siguiente(desde, usuarios.len(), self.marketplace.cantidad_usuarios());
+            PaginaReporte { resultados: ranking_por_reputacion(&usuarios, rol, n), siguiente }
+        }
+
+        /// Consulta los `n` productos con más unidades vendidas entre los productos
+        /// con id a partir de `desde` (como máximo `MAX_RESULTADOS_POR_PAGINA`).
+        ///
+        /// Las unidades son las que acumula el marketplace al concretarse cada
+        /// venta, así que cada llamada lee una sola página de productos; el
+        /// ranking completo se arma con las páginas como en `top_vendedores`.
+        /// A igual cantidad de unidades, queda primero el producto con menor id.
+        #[ink(message)]
+        pub fn productos_mas_vendidos(&self, desde: u32, cantidad: u32, n: u32) -> PaginaReporte<ProductoVendido> {
+            let productos = self.marketplace.listar_productos(desde, cantidad);
+            let unidades: BTreeMap<u32, u32> = self.marketplace.listar_unidades_vendidas(desde, cantidad).into_iter().collect();
+            let siguiente = posicion_siguiente(desde, productos.len(), self.marketplace.cantidad_productos());
+            PaginaReporte { resultados: mas_vendidos(&productos, &unidades, n), siguiente }
+        }
+
+        /// Consulta las estadísticas de ventas de las categorías con id a partir
+        /// de `desde` (como máximo `MAX_RESULTADOS_POR_PAGINA`), en orden de id.
+        ///
+        /// Para cada categoría con ventas informa cantidad de órdenes, unidades
+        /// vendidas, volumen bruto por moneda y calificación promedio recibida por
+        /// los vendedores en esas órdenes. Solo se cuentan las ventas concretadas,
+        /// que el marketplace acumula por categoría al liberar cada pago; las
+        /// subcategorías se informan por separado.
+        #[ink(message)]
+        pub fn estadisticas_por_categoria(&self, desde: u32, cantidad: u32) -> PaginaReporte<EstadisticaCategoria> {
+            let categorias = self.marketplace.listar_categorias(desde, cantidad);
+            let ventas: BTreeMap<u32, VentasCategoria> =
+                self.marketplace.listar_ventas_por_categoria(desde, cantidad).into_iter().collect();
+            let siguiente = posicion_siguiente(desde, categorias.len(), self.marketplace.cantidad_categorias());
+            PaginaReporte { resultados: estadisticas_por_categoria(&categorias, &ventas), siguiente }
+        }
+
+        /// Consulta cuántas órdenes tiene un usuario como comprador y como
+        /// vendedor, desglosadas por estado.
+        #[ink(message)]
+        pub fn ordenes_por_usuario(&self, usuario: AccountId) -> ResumenOrdenesUsuario {
+            ResumenOrdenesUsuario {
+                cuenta: usuario,
+                como_comprador: ConteoPorEstado::contar(&self.ordenes_como_comprador(usuario)),
+                como_vendedor: ConteoPorEstado::contar(&self.ordenes_como_vendedor(usuario)),
+            }
+        }
+
+        /// Recorre de a páginas las órdenes de `usuario` como comprador.
+        fn ordenes_como_comprador(&self, usuario: AccountId) -> Vec<Orden> {
+            recorrer_paginas(self.marketplace.cantidad_ordenes_como_comprador(usuario), |desde| {
+                self.marketplace.listar_ordenes_como_comprador(usuario, desde, MAX_RESULTADOS_POR_PAGINA)
+            })
+        }
+
+        /// Recorre de a páginas las órdenes de `usuario` como vendedor.
+        fn ordenes_como_vendedor(&self, usuario: AccountId) -> Vec<Orden> {
+            recorrer_paginas(self.marketplace.cantidad_ordenes_como_vendedor(usuario), |desde| {
+                self.marketplace.listar_ordenes_como_vendedor(usuario, desde, MAX_RESULTADOS_POR_PAGINA)
+            })
+        }
+
+        /// Variante paginada de `ordenes_por_usuario` sobre todos los usuarios
+        /// registrados, en orden de registro. Devuelve como máximo
+        /// `MAX_RESULTADOS_POR_PAGINA` resúmenes a partir de la posición `desde`.
+        /// Solo lee las órdenes de los usuarios de la página, con los índices
+        /// por comprador y por vendedor del marketplace.
+        #[ink(message)]
+        pub fn ordenes_por_usuarios(&self, desde: u32, cantidad: u32) -> Vec<ResumenOrdenesUsuario> {
+            self.marketplace
+                .listar_usuarios(desde, cantidad)
+                .into_iter()
+                .map(|u| self.ordenes_por_usuario(u.direccion))
+                .collect()
+        }
+    }
+
+    // ────────────────
+    // CÁLCULO DE REPORTES
+    // ────────────────
+
+    /// Arma el ranking de los `n` usuarios con mejor promedio en el rol indicado.
+    /// A igual promedio, queda primero quien tiene más calificaciones.
+    fn ranking_por_reputacion(usuarios: &[Usuario], rol: RolUsuario, n: u32) -> Vec<ReputacionUsuario> {
+        let mut ranking: Vec<ReputacionUsuario> = usuarios
+            .iter()
+            .filter_map(|u| {
+                let (promedio, calificaciones) = match rol {
+                    RolUsuario::Comprador => (u.promedio_como_comprador(), u.calificaciones_como_comprador),
+                    _ => (u.promedio_como_vendedor(), u.calificaciones_como_vendedor),
+                };
+                if calificaciones < MINIMO_CALIFICACIONES_RANKING {
+                    return None;
+                }
+                promedio.map(|promedio| ReputacionUsuario {
+                    cuenta: u.direccion,
+                    promedio,
+                    calificaciones,
+                })
+            })
+            .collect();
+        ranking.sort_by(|a, b| {
+            b.promedio
+                .cmp(&a.promedio)
+                .then(b.calificaciones.cmp(&a.calificaciones))
+        });
+        ranking.truncate(n as usize);
+        ranking
+    }
+
+    /// Arma el ranking de los `n` productos con más unidades vendidas según
+    /// `unidades` (por id de producto). Los productos sin ventas no aparecen.
+    fn mas_vendidos(productos: &[Producto], unidades: &BTreeMap<u32, u32>, n: u32) -> Vec<ProductoVendido> {
+        let mut ranking: Vec<ProductoVendido> = productos
+            .iter()
+            .filter_map(|p| {
+                unidades.get(&p.id).filter(|u| **u > 0).map(|unidades| ProductoVendido {
+                    producto_id: p.id,
+                    nombre: p.nombre.clone(),
+                    vendedor: p.vendedor,
+                    unidades: *unidades,
+                })
+            })
+            .collect();
+        ranking.sort_by_key(|p| (core::cmp::Reverse(p.unidades), p.producto_id));
+        ranking.truncate(n as usize);
+        ranking
+    }
+
+    /// Arma las estadísticas de las categorías con ventas según `ventas` (por id
+    /// de categoría), en el orden de `categorias`.
+    fn estadisticas_por_categoria(
+        categorias: &[Categoria],
+        ventas: &BTreeMap<u32, VentasCategoria>,
+    ) -> Vec<EstadisticaCategoria> {
+        categorias
+            .iter()
+            .filter_map(|categoria| {
+                let ventas = ventas.get(&categoria.id).filter(|v| v.ordenes > 0)?;
+                Some(EstadisticaCategoria {
+                    categoria_id: categoria.id,
+                    categoria: categoria.nombre.clone(),
+                    ordenes: ventas.ordenes,
+                    unidades: ventas.unidades,
+                    volumen: ventas.volumen,
+                    volumen_psp22: ventas.volumen_psp22.clone(),
+                    calificacion_promedio: ventas.calificacion_promedio(),
+                })
+            })
+            .collect()
+    }
+
+    /// Posición desde la que pedir la página siguiente de un índice de `total`
+    /// entradas, después de leer `leidos` a partir de `desde`; `None` si no quedan.
+    fn posicion_siguiente(desde: u32, leidos: usize, total: u32) -> Option<u32> {
+        let siguiente = desde.saturating_add(u32::try_from(leidos).unwrap_or(u32::MAX));
+        (leidos > 0 && siguiente < total).then_some(siguiente)
+    }
+
+    /// Junta las páginas de un índice de `total` entradas, pidiendo cada una
+    /// con `pagina(desde)` hasta completarlo o recibir una página vacía.
+    fn recorrer_paginas<T>(total: u32, pagina: impl Fn(u32) -> Vec<T>) -> Vec<T> {
+        let mut elementos = Vec::new();
+        let mut desde = 0;
+        while desde < total {
+            let leidos = pagina(desde);
+            if leidos.is_empty() {
+                break;
+            }
+            desde = desde.saturating_add(u32::try_from(leidos.len()).unwrap_or(u32::MAX));
+            elementos.extend(leidos);
         }
+        elementos
+    }
+
+    // ────────────────
+    // ESTRUCTURAS DE REPORTES
+    // ────────────────
+
+    /// Cantidad de órdenes desglosada por estado.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct ConteoPorEstado {
+        /// Total de órdenes.
+        pub total: u32,
+        /// Órdenes pendientes.
+        pub pendientes: u32,
+        /// Órdenes enviadas.
+        pub enviadas: u32,
+        /// Órdenes recibidas.
+        pub recibidas: u32,
+        /// Órdenes canceladas.
+        pub canceladas: u32,
+        /// Órdenes con una disputa abierta.
+        pub en_disputa: u32,
+        /// Órdenes con una devolución solicitada, aceptada o en camino.
+        pub en_devolucion: u32,
+        /// Órdenes devueltas y reembolsadas.
+        pub devueltas: u32,
+    }
+    impl ConteoPorEstado {
+        /// Cuenta las órdenes de la lista según su estado.
+        pub fn contar(ordenes: &[Orden]) -> Self {
+            let mut conteo = Self::default();
+            for orden in ordenes {
+                conteo.sumar(&orden.estado);
+            }
+            conteo
+        }
+
+        /// Suma una orden en el estado indicado.
+        fn sumar(&mut self, estado: &EstadoOrden) {
+            self.total = self.total.saturating_add(1);
+            let contador = match estado {
+                EstadoOrden::Pendiente => &mut self.pendientes,
+                EstadoOrden::Enviada => &mut self.enviadas,
+                EstadoOrden::Recibida => &mut self.recibidas,
+                EstadoOrden::Cancelada => &mut self.canceladas,
+                EstadoOrden::EnDisputa => &mut self.en_disputa,
+                EstadoOrden::DevolucionSolicitada
+                | EstadoOrden::DevolucionAceptada
+                | EstadoOrden::DevolucionEnviada => &mut self.en_devolucion,
+                EstadoOrden::Devuelta => &mut self.devueltas,
+            };
+            *contador = contador.saturating_add(1);
+        }
+    }
+
+    /// Órdenes de un usuario según el rol que ocupa en cada una.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct ResumenOrdenesUsuario {
+        /// Cuenta del usuario.
+        pub cuenta: AccountId,
+        /// Órdenes en las que el usuario es comprador.
+        pub como_comprador: ConteoPorEstado,
+        /// Órdenes en las que el usuario es vendedor.
+        pub como_vendedor: ConteoPorEstado,
+    }
+
+    /// Página de un reporte, con la posición desde la que pedir la siguiente.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct PaginaReporte<T> {
+        /// Resultados calculados sobre la página.
+        pub resultados: Vec<T>,
+        /// Valor de `desde` para pedir la página siguiente; `None` si no quedan más.
+        pub siguiente: Option<u32>,
+    }
+
+    /// Estadísticas de ventas de una categoría.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct EstadisticaCategoria {
+        /// Identificador de la categoría.
+        pub categoria_id: u32,
+        /// Nombre de la categoría.
+        pub categoria: String,
+        /// Cantidad de órdenes concretadas.
+        pub ordenes: u32,
+        /// Unidades vendidas.
+        pub unidades: u32,
+        /// Volumen bruto vendido en la moneda nativa.
+        pub volumen: Balance,
+        /// Volumen bruto vendido en cada token PSP22, en el orden en que aparecen.
+        pub volumen_psp22: Vec<(AccountId, Balance)>,
+        /// Calificación promedio de los vendedores multiplicada por 100,
+        /// o `None` si ninguna orden de la categoría fue calificada.
+        pub calificacion_promedio: Option<u32>,
+    }
+    /// Producto con la cantidad de unidades vendidas, para el reporte de más vendidos.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct ProductoVendido {
+        /// Identificador del producto.
+        pub producto_id: u32,
+        /// Nombre del producto.
+        pub nombre: String,
+        /// Dirección del vendedor.
+        pub vendedor: AccountId,
+        /// Unidades vendidas en ventas concretadas.
+        pub unidades: u32,
+    }
+
+    /// Reputación de un usuario en un rol, tal como aparece en los rankings.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct ReputacionUsuario {
+        /// Cuenta del usuario.
+        pub cuenta: AccountId,
+        /// Promedio de calificaciones multiplicado por 100.
+        pub promedio: u32,
+        /// Cantidad de calificaciones recibidas en el rol.
+        pub calificaciones: u32,
     }
 
-    /// Unit tests in Rust are normally defined within such a `#[cfg(test)]`
-    /// module and test functions are marked with a `#[test]` attribute.
-    /// The below code is technically just normal Rust code.
     #[cfg(test)]
     mod tests {
-        /// Imports all the definitions from the outer scope so we can use them here.
         use super::*;
+        use marketplace_principal::marketplace_principal::{LineaOrden, MarcaTiempo};
 
-        /// We test if the default constructor does its job.
+        // Las llamadas entre contratos no están soportadas en el entorno off-chain;
+        // las consultas al marketplace se cubren en los tests e2e.
         #[ink::test]
-        fn default_works() {
-            let reportes_view = ReportesView::default();
-            assert_eq!(reportes_view.get(), false);
+        fn new_guarda_direccion_del_marketplace() {
+            let marketplace = AccountId::from([0x10; 32]);
+            let reportes = ReportesView::new(marketplace);
+            assert_eq!(reportes.marketplace(), marketplace);
+        }
+
+        // Función auxiliar para armar un usuario con reputación dada
+        fn usuario(byte: u8, suma_vendedor: u32, cant_vendedor: u32, suma_comprador: u32, cant_comprador: u32) -> Usuario {
+            Usuario {
+                direccion: AccountId::from([byte; 32]),
+                rol: RolUsuario::Ambos,
+                reputacion_como_comprador: suma_comprador,
+                reputacion_como_vendedor: suma_vendedor,
+                calificaciones_como_comprador: cant_comprador,
+                calificaciones_como_vendedor: cant_vendedor,
+            }
         }
 
-        /// We test a simple use case of our contract.
         #[ink::test]
-        fn it_works() {
-            let mut reportes_view = ReportesView::new(false);
-            assert_eq!(reportes_view.get(), false);
-            reportes_view.flip();
-            assert_eq!(reportes_view.get(), true);
+        fn ranking_vendedores_ordena_por_promedio() {
+            let usuarios = [
+                usuario(1, 12, 3, 0, 0),  // 4,00
+                usuario(2, 15, 3, 0, 0),  // 5,00
+                usuario(3, 18, 4, 0, 0),  // 4,50
+            ];
+            let top = ranking_por_reputacion(&usuarios, RolUsuario::Vendedor, 5);
+            let cuentas: Vec<_> = top.iter().map(|r| r.cuenta).collect();
+            assert_eq!(cuentas, [AccountId::from([2; 32]), AccountId::from([3; 32]), AccountId::from([1; 32])]);
+            assert_eq!(top[1].promedio, 450);
+            assert_eq!(top[1].calificaciones, 4);
         }
-    }
 
+        #[ink::test]
+        fn ranking_excluye_usuarios_con_pocas_calificaciones() {
+            let usuarios = [
+                usuario(1, 5, 1, 0, 0),   // una sola calificación de 5
+                usuario(2, 9, 3, 0, 0),   // 3,00 con el mínimo exigido
+            ];
+            let top = ranking_por_reputacion(&usuarios, RolUsuario::Vendedor, 5);
+            assert_eq!(top.len(), 1);
+            assert_eq!(top[0].cuenta, AccountId::from([2; 32]));
+        }
 
-    /// This is how you'd write end-to-end (E2E) or integration tests for ink! contracts.
+        #[ink::test]
+        fn ranking_compradores_usa_reputacion_de_comprador() {
+            let usuarios = [
+                usuario(1, 15, 3, 6, 3),
+                usuario(2, 6, 3, 12, 3),
+            ];
+            let top = ranking_por_reputacion(&usuarios, RolUsuario::Comprador, 1);
+            assert_eq!(top.len(), 1);
+            assert_eq!(top[0].cuenta, AccountId::from([2; 32]));
+            assert_eq!(top[0].promedio, 400);
+        }
+
+        #[ink::test]
+        fn ranking_desempata_por_cantidad_de_calificaciones() {
+            let usuarios = [
+                usuario(1, 12, 3, 0, 0),
+                usuario(2, 24, 6, 0, 0),
+            ];
+            let top = ranking_por_reputacion(&usuarios, RolUsuario::Vendedor, 5);
+            assert_eq!(top[0].cuenta, AccountId::from([2; 32]));
+            assert!(ranking_por_reputacion(&usuarios, RolUsuario::Vendedor, 0).is_empty());
+        }
+
+        // Función auxiliar para armar una orden en un estado dado
+        fn orden(id: u32, producto_id: u32, cantidad: u32, estado: EstadoOrden) -> Orden {
+            let linea = LineaOrden { producto_id, cantidad, precio_unitario: 0 };
+            let mut orden = Orden::new(
+                id,
+                AccountId::from([0x02; 32]),
+                AccountId::from([0x01; 32]),
+                Vec::from([linea]),
+                MarcaTiempo::default(),
+            );
+            orden.estado = estado;
+            orden
+        }
+
+        #[ink::test]
+        fn conteo_por_estado_desglosa_ordenes() {
+            let ordenes = [
+                orden(0, 1, 1, EstadoOrden::Pendiente),
+                orden(1, 1, 1, EstadoOrden::Recibida),
+                orden(2, 1, 1, EstadoOrden::Recibida),
+                orden(3, 1, 1, EstadoOrden::Cancelada),
+                orden(4, 1, 1, EstadoOrden::DevolucionEnviada),
+                orden(5, 1, 1, EstadoOrden::Devuelta),
+            ];
+            let conteo = ConteoPorEstado::contar(&ordenes);
+            assert_eq!(
+                conteo,
+                ConteoPorEstado {
+                    total: 6,
+                    pendientes: 1,
+                    enviadas: 0,
+                    recibidas: 2,
+                    canceladas: 1,
+                    en_disputa: 0,
+                    en_devolucion: 1,
+                    devueltas: 1,
+                }
+            );
+        }
+
+
+        // Función auxiliar para armar un producto publicado
+        fn producto(id: u32, nombre: &str) -> Producto {
+            Producto::new(id, nombre.into(), "D".into(), 10, 5, 0, AccountId::from([0x01; 32]))
+        }
+
+        #[ink::test]
+        fn mas_vendidos_ordena_por_unidades_y_desempata_por_id() {
+            let productos = [producto(4, "Mesa"), producto(5, "Silla"), producto(7, "Lámpara"), producto(8, "Sin ventas")];
+            let unidades = BTreeMap::from([(4, 3), (5, 9), (7, 3), (9, 20)]);
+
+            let ranking = mas_vendidos(&productos, &unidades, 5);
+            let ids: Vec<_> = ranking.iter().map(|p| (p.producto_id, p.unidades)).collect();
+            assert_eq!(ids, [(5, 9), (4, 3), (7, 3)]);
+            assert_eq!(ranking[0].nombre, "Silla");
+            assert_eq!(mas_vendidos(&productos, &unidades, 1).len(), 1);
+        }
+
+        #[ink::test]
+        fn estadisticas_arma_una_fila_por_categoria_con_ventas() {
+            let token = AccountId::from([0x70; 32]);
+            let categorias = [
+                Categoria { id: 0, nombre: "Hogar".into(), padre: None },
+                Categoria { id: 1, nombre: "Libros".into(), padre: None },
+                Categoria { id: 2, nombre: "Tecnología".into(), padre: None },
+            ];
+            let ventas = BTreeMap::from([
+                (0, VentasCategoria {
+                    ordenes: 2,
+                    unidades: 5,
+                    volumen: 400,
+                    volumen_psp22: Vec::from([(token, 1_000)]),
+                    suma_puntajes: 9,
+                    cantidad_puntajes: 2,
+                }),
+                (2, VentasCategoria { ordenes: 1, unidades: 1, volumen: 50, ..Default::default() }),
+            ]);
+
+            let estadisticas = estadisticas_por_categoria(&categorias, &ventas);
+            assert_eq!(estadisticas.len(), 2);
+            let hogar = &estadisticas[0];
+            assert_eq!((hogar.categoria_id, hogar.categoria.as_str()), (0, "Hogar"));
+            assert_eq!((hogar.ordenes, hogar.unidades, hogar.volumen), (2, 5, 400));
+            assert_eq!(hogar.volumen_psp22, [(token, 1_000)]);
+            assert_eq!(hogar.calificacion_promedio, Some(450));
+            assert_eq!(estadisticas[1].categoria, "Tecnología");
+            assert_eq!(estadisticas[1].calificacion_promedio, None);
+        }
+
+        #[ink::test]
+        fn paginas_indican_desde_donde_seguir() {
+            assert_eq!(posicion_siguiente(0, 100, 250), Some(100));
+            assert_eq!(posicion_siguiente(200, 50, 250), None);
+            assert_eq!(posicion_siguiente(300, 0, 250), None);
+
+            let leidas = core::cell::Cell::new(0);
+            let todos = recorrer_paginas(7, |desde| {
+                leidas.set(leidas.get() + 1);
+                (desde..7.min(desde + 3)).collect::<Vec<u32>>()
+            });
+            assert_eq!(todos, (0..7).collect::<Vec<_>>());
+            assert_eq!(leidas.get(), 3);
+        }
+    }
+
+    /// Tests end-to-end: despliegan ambos contratos y consultan el marketplace
+    /// a través de ReportesView.
     ///
-    /// When running these you need to make sure that you:
-    /// - Compile the tests with the `e2e-tests` feature flag enabled (`--features e2e-tests`)
-    /// - Are running a Substrate node which contains `pallet-contracts` in the background
+    /// Para ejecutarlos hace falta:
+    /// - Compilar con el feature `e2e-tests` (`--features e2e-tests`).
+    /// - Tener corriendo un nodo Substrate con `pallet-contracts`.
     #[cfg(all(test, feature = "e2e-tests"))]
     mod e2e_tests {
-        /// Imports all the definitions from the outer scope so we can use them here.
         use super::*;
-
-        /// A helper function used for calling contract messages.
         use ink_e2e::ContractsBackend;
+        use marketplace_principal::marketplace_principal::{MarketplacePrincipal, RolUsuario};
 
-        /// The End-to-End test `Result` type.
         type E2EResult<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
-        /// We test that we can upload and instantiate the contract using its default constructor.
         #[ink_e2e::test]
-        async fn default_works(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
-            // Given
-            let mut constructor = ReportesViewRef::default();
-
-            // When
-            let contract = client
-                .instantiate("reportes_view", &ink_e2e::alice(), &mut constructor)
+        async fn lee_usuarios_del_marketplace(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            // Despliega el marketplace y registra a Alice
+            let mut constructor = MarketplacePrincipalRef::new();
+            let marketplace = client
+                .instantiate("marketplace_principal", &ink_e2e::alice(), &mut constructor)
                 .submit()
                 .await
-                .expect("instantiate failed");
-            let call_builder = contract.call_builder::<ReportesView>();
-
-            // Then
-            let get = call_builder.get();
-            let get_result = client.call(&ink_e2e::alice(), &get).dry_run().await?;
-            assert!(matches!(get_result.return_value(), false));
-
-            Ok(())
-        }
+                .expect("instantiate marketplace failed");
+            let mut marketplace_call = marketplace.call_builder::<MarketplacePrincipal>();
+            let registrar = marketplace_call.registrar_usuario(RolUsuario::Vendedor);
+            client
+                .call(&ink_e2e::alice(), &registrar)
+                .submit()
+                .await
+                .expect("registrar_usuario failed");
 
-        /// We test that we can read and write a value from the on-chain contract.
-        #[ink_e2e::test]
-        async fn it_works(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
-            // Given
-            let mut constructor = ReportesViewRef::new(false);
-            let contract = client
+            // Despliega los reportes apuntando al marketplace
+            let mut constructor = ReportesViewRef::new(marketplace.account_id);
+            let reportes = client
                 .instantiate("reportes_view", &ink_e2e::bob(), &mut constructor)
                 .submit()
                 .await
-                .expect("instantiate failed");
-            let mut call_builder = contract.call_builder::<ReportesView>();
+                .expect("instantiate reportes failed");
+            let reportes_call = reportes.call_builder::<ReportesView>();
 
-            let get = call_builder.get();
-            let get_result = client.call(&ink_e2e::bob(), &get).dry_run().await?;
-            assert!(matches!(get_result.return_value(), false));
+            let alice = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let consulta = reportes_call.obtener_usuario(alice);
+            let usuario = client.call(&ink_e2e::bob(), &consulta).dry_run().await?.return_value();
+            assert_eq!(usuario.map(|u| u.rol), Some(RolUsuario::Vendedor));
 
-            // When
-            let flip = call_builder.flip();
-            let _flip_result = client
-                .call(&ink_e2e::bob(), &flip)
-                .submit()
-                .await
-                .expect("flip failed");
+            let consulta = reportes_call.cantidad_ordenes();
+            let cantidad = client.call(&ink_e2e::bob(), &consulta).dry_run().await?.return_value();
+            assert_eq!(cantidad, 0);
 
-            // Then
-            let get = call_builder.get();
-            let get_result = client.call(&ink_e2e::bob(), &get).dry_run().await?;
-            assert!(matches!(get_result.return_value(), true));
+            let consulta = reportes_call.total_comisiones_cobradas(Moneda::Nativa);
+            let comisiones = client.call(&ink_e2e::bob(), &consulta).dry_run().await?.return_value();
+            assert_eq!(comisiones, 0);
 
             Ok(())
         }
@@ -0,0 +1,257 @@
+/*
+Token PSP22 mínimo.
+Token fungible que implementa la interfaz estándar PSP22, para usarlo como
+moneda de pago alternativa en MarketplacePrincipal (por ejemplo, una
+stablecoin). Los nombres de los mensajes y de los errores siguen el estándar
+para que los selectores sean compatibles con cualquier otro token PSP22.
+*/
+
+#![cfg_attr(not(feature = "std"), no_std, no_main)]
+
+use ink::prelude::string::String;
+use ink::prelude::vec::Vec;
+use ink::primitives::AccountId;
+
+/// Tipo de los montos del token (el mismo `Balance` del entorno por defecto).
+pub type Balance = <ink::env::DefaultEnvironment as ink::env::Environment>::Balance;
+
+/// Errores definidos por el estándar PSP22.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+pub enum PSP22Error {
+    /// Error propio de la implementación.
+    Custom(String),
+    /// La cuenta de origen no tiene saldo suficiente.
+    InsufficientBalance,
+    /// El spender no tiene allowance suficiente.
+    InsufficientAllowance,
+    /// El destinatario es la cuenta cero.
+    ZeroRecipientAddress,
+    /// El origen es la cuenta cero.
+    ZeroSenderAddress,
+    /// El destinatario rechazó la transferencia.
+    SafeTransferCheckFailed(String),
+}
+
+/// Interfaz estándar PSP22. Cualquier contrato que la implemente puede
+/// llamarse con `ink::contract_ref!(PSP22)`.
+#[ink::trait_definition]
+pub trait PSP22 {
+    /// Retorna la cantidad total de tokens emitidos.
+    #[ink(message)]
+    fn total_supply(&self) -> Balance;
+
+    /// Retorna el saldo de `owner`.
+    #[ink(message)]
+    fn balance_of(&self, owner: AccountId) -> Balance;
+
+    /// Retorna cuánto puede transferir `spender` en nombre de `owner`.
+    #[ink(message)]
+    fn allowance(&self, owner: AccountId, spender: AccountId) -> Balance;
+
+    /// Transfiere `value` tokens del caller a `to`.
+    #[ink(message)]
+    fn transfer(&mut self, to: AccountId, value: Balance, data: Vec<u8>) -> Result<(), PSP22Error>;
+
+    /// Transfiere `value` tokens de `from` a `to`, consumiendo el allowance del caller.
+    #[ink(message)]
+    fn transfer_from(
+        &mut self,
+        from: AccountId,
+        to: AccountId,
+        value: Balance,
+        data: Vec<u8>,
+    ) -> Result<(), PSP22Error>;
+
+    /// Permite a `spender` transferir hasta `value` tokens del caller.
+    #[ink(message)]
+    fn approve(&mut self, spender: AccountId, value: Balance) -> Result<(), PSP22Error>;
+}
+
+#[ink::contract]
+pub mod token_psp22 {
+    use super::{PSP22Error, PSP22};
+    use ink::prelude::vec::Vec;
+    use ink::storage::Mapping;
+
+    /// Estructura principal del token.
+    #[ink(storage)]
+    pub struct TokenPsp22 {
+        /// Cantidad total de tokens emitidos.
+        suministro_total: Balance,
+        /// Saldo de cada cuenta.
+        saldos: Mapping<AccountId, Balance>,
+        /// Allowance de cada par (dueño, spender).
+        allowances: Mapping<(AccountId, AccountId), Balance>,
+    }
+
+    impl TokenPsp22 {
+        /// Crea el token y le asigna todo el suministro inicial a quien lo despliega.
+        #[ink(constructor)]
+        pub fn new(suministro_total: Balance) -> Self {
+            let caller = Self::env().caller();
+            let mut saldos = Mapping::default();
+            saldos.insert(caller, &suministro_total);
+
+            //Evento
+            Self::env().emit_event(Transfer { from: None, to: Some(caller), value: suministro_total });
+            Self {
+                suministro_total,
+                saldos,
+                allowances: Mapping::default(),
+            }
+        }
+
+        /// Mueve `value` tokens de `from` a `to` y emite `Transfer`.
+        fn mover(&mut self, from: AccountId, to: AccountId, value: Balance) -> Result<(), PSP22Error> {
+            let saldo_origen = self.saldos.get(from).unwrap_or(0);
+            if saldo_origen < value {
+                return Err(PSP22Error::InsufficientBalance);
+            }
+            if from == to || value == 0 {
+                return Ok(());
+            }
+            let saldo_destino = self.saldos.get(to).unwrap_or(0);
+            self.saldos.insert(from, &(saldo_origen - value));
+            self.saldos.insert(to, &saldo_destino.saturating_add(value));
+
+            //Evento
+            self.env().emit_event(Transfer { from: Some(from), to: Some(to), value });
+            Ok(())
+        }
+    }
+
+    impl PSP22 for TokenPsp22 {
+        #[ink(message)]
+        fn total_supply(&self) -> Balance {
+            self.suministro_total
+        }
+
+        #[ink(message)]
+        fn balance_of(&self, owner: AccountId) -> Balance {
+            self.saldos.get(owner).unwrap_or(0)
+        }
+
+        #[ink(message)]
+        fn allowance(&self, owner: AccountId, spender: AccountId) -> Balance {
+            self.allowances.get((owner, spender)).unwrap_or(0)
+        }
+
+        #[ink(message)]
+        fn transfer(&mut self, to: AccountId, value: Balance, _data: Vec<u8>) -> Result<(), PSP22Error> {
+            let from = self.env().caller();
+            self.mover(from, to, value)
+        }
+
+        #[ink(message)]
+        fn transfer_from(
+            &mut self,
+            from: AccountId,
+            to: AccountId,
+            value: Balance,
+            _data: Vec<u8>,
+        ) -> Result<(), PSP22Error> {
+            let spender = self.env().caller();
+            if spender == from {
+                return self.mover(from, to, value);
+            }
+            let disponible = self.allowance(from, spender);
+            if disponible < value {
+                return Err(PSP22Error::InsufficientAllowance);
+            }
+            self.mover(from, to, value)?;
+            let restante = disponible - value;
+            self.allowances.insert((from, spender), &restante);
+
+            //Evento
+            self.env().emit_event(Approval { owner: from, spender, amount: restante });
+            Ok(())
+        }
+
+        #[ink(message)]
+        fn approve(&mut self, spender: AccountId, value: Balance) -> Result<(), PSP22Error> {
+            let owner = self.env().caller();
+            if owner == spender {
+                return Ok(());
+            }
+            self.allowances.insert((owner, spender), &value);
+
+            //Evento
+            self.env().emit_event(Approval { owner, spender, amount: value });
+            Ok(())
+        }
+    }
+
+    // ────────────────
+    // EVENTOS
+    // ────────────────
+
+    /// Se movieron tokens; `from` es `None` al emitir y `to` es `None` al quemar.
+    #[ink(event)]
+    pub struct Transfer {
+        #[ink(topic)]
+        from: Option<AccountId>,
+        #[ink(topic)]
+        to: Option<AccountId>,
+        value: Balance,
+    }
+
+    /// Cambió el allowance de `spender` sobre los tokens de `owner`.
+    #[ink(event)]
+    pub struct Approval {
+        #[ink(topic)]
+        owner: AccountId,
+        #[ink(topic)]
+        spender: AccountId,
+        amount: Balance,
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use ink::env::test;
+
+        // Función auxiliar: Alice despliega el token con 1_000 unidades
+        fn setup_token() -> TokenPsp22 {
+            let accounts = test::default_accounts::<ink::env::DefaultEnvironment>();
+            test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            TokenPsp22::new(1_000)
+        }
+
+        #[ink::test]
+        fn new_asigna_el_suministro_al_creador() {
+            let token = setup_token();
+            let accounts = test::default_accounts::<ink::env::DefaultEnvironment>();
+            assert_eq!(token.total_supply(), 1_000);
+            assert_eq!(token.balance_of(accounts.alice), 1_000);
+            assert_eq!(token.balance_of(accounts.bob), 0);
+        }
+
+        #[ink::test]
+        fn transfer_mueve_saldo() {
+            let mut token = setup_token();
+            let accounts = test::default_accounts::<ink::env::DefaultEnvironment>();
+
+            assert_eq!(token.transfer(accounts.bob, 300, Vec::new()), Ok(()));
+            assert_eq!(token.balance_of(accounts.alice), 700);
+            assert_eq!(token.balance_of(accounts.bob), 300);
+            assert_eq!(token.transfer(accounts.bob, 701, Vec::new()), Err(PSP22Error::InsufficientBalance));
+        }
+
+        #[ink::test]
+        fn transfer_from_consume_allowance() {
+            let mut token = setup_token();
+            let accounts = test::default_accounts::<ink::env::DefaultEnvironment>();
+            token.approve(accounts.bob, 200).unwrap();
+
+            test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(
+                token.transfer_from(accounts.alice, accounts.charlie, 201, Vec::new()),
+                Err(PSP22Error::InsufficientAllowance)
+            );
+            assert_eq!(token.transfer_from(accounts.alice, accounts.charlie, 150, Vec::new()), Ok(()));
+            assert_eq!(token.balance_of(accounts.charlie), 150);
+            assert_eq!(token.allowance(accounts.alice, accounts.bob), 50);
+        }
+    }
+}